@@ -1,4 +1,5 @@
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
 use egui::Color32;
 use log::SetLoggerError;
@@ -46,6 +47,15 @@ type GlobalLog = Vec<(log::Level, String)>;
 
 static LOG: Mutex<GlobalLog> = Mutex::new(Vec::new());
 
+/// Incremented whenever [`LOG`] is changed in any way other than appending,
+/// so the ui knows its cached rows no longer line up with the log.
+/// Must only be changed while holding the [`LOG`] lock.
+static LOG_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+fn invalidate_line_cache() {
+    LOG_GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
 fn log_ui() -> &'static Mutex<LoggerUi> {
     static LOGGER_UI: std::sync::OnceLock<Mutex<LoggerUi>> = std::sync::OnceLock::new();
     LOGGER_UI.get_or_init(Default::default)
@@ -71,6 +81,36 @@ where
     }
 }
 
+/// A log entry prepared for display, kept in the same order as [`LOG`].
+struct CachedLine {
+    level: log::Level,
+    /// The entry as shown in the ui.
+    text: String,
+    /// The lowercased message, only kept while a case-insensitive search is active.
+    message_lower: Option<String>,
+    /// Height of the wrapped text at [`LoggerUi::wrap_width`].
+    height: Option<f32>,
+}
+
+impl CachedLine {
+    fn galley(&self, ui: &egui::Ui, wrap_width: f32) -> Arc<egui::Galley> {
+        let text = match self.level {
+            log::Level::Warn => egui::RichText::new(&self.text).color(Color32::YELLOW),
+            log::Level::Error => egui::RichText::new(&self.text).color(Color32::RED),
+            _ => egui::RichText::new(&self.text),
+        };
+        egui::WidgetText::from(text).into_galley(ui, None, wrap_width, egui::FontSelection::Default)
+    }
+}
+
+/// The settings the displayed entries were filtered with.
+struct FilterKey {
+    loglevels: [bool; log::Level::Trace as usize],
+    search_term: String,
+    search_case_sensitive: bool,
+    search_use_regex: bool,
+}
+
 struct LoggerUi {
     loglevels: [bool; log::Level::Trace as usize],
     search_term: String,
@@ -78,6 +118,19 @@ struct LoggerUi {
     search_case_sensitive: bool,
     search_use_regex: bool,
     max_log_length: usize,
+    line_cache: Vec<CachedLine>,
+    line_cache_generation: usize,
+    filter_key: Option<FilterKey>,
+    /// Indices into `line_cache` of the entries passing the filter.
+    displayed: Vec<usize>,
+    /// Number of log entries already run through the filter.
+    filtered_len: usize,
+    /// Width the cached heights were measured at.
+    wrap_width: f32,
+    /// Top of each laid out displayed entry, relative to the top of the scroll area.
+    row_tops: Vec<f32>,
+    /// Total height of the entries in `row_tops`.
+    rows_height: f32,
 }
 
 impl Default for LoggerUi {
@@ -89,6 +142,14 @@ impl Default for LoggerUi {
             regex: None,
             search_use_regex: false,
             max_log_length: 1000,
+            line_cache: Vec::new(),
+            line_cache_generation: 0,
+            filter_key: None,
+            displayed: Vec::new(),
+            filtered_len: 0,
+            wrap_width: 0.0,
+            row_tops: Vec::new(),
+            rows_height: 0.0,
         }
     }
 }
@@ -98,11 +159,12 @@ impl LoggerUi {
         try_mut_log(|logs| {
             let dropped_entries = logs.len().saturating_sub(self.max_log_length);
             drop(logs.drain(..dropped_entries));
+            self.drop_front(dropped_entries);
         });
 
         ui.horizontal(|ui| {
             if ui.button("Clear").clicked() {
-                try_mut_log(|logs| logs.clear());
+                clear();
             }
             ui.menu_button("Log Levels", |ui| {
                 for level in LEVELS {
@@ -155,45 +217,51 @@ impl LoggerUi {
 
         ui.horizontal(|ui| {
             if ui.button("Sort").clicked() {
-                try_mut_log(|logs| logs.sort());
+                try_mut_log(|logs| {
+                    logs.sort();
+                    invalidate_line_cache();
+                });
             }
         });
 
         ui.separator();
 
-        let mut logs_displayed: usize = 0;
+        try_get_log(|logs| self.update_displayed(logs));
+
+        let logs_displayed = self.displayed.len();
 
         egui::ScrollArea::vertical()
             .auto_shrink([false, true])
             .max_height(ui.available_height() - 30.0)
             .stick_to_bottom(true)
-            .show(ui, |ui| {
-                try_get_log(|logs| {
-                    logs.iter().for_each(|(level, string)| {
-                        if (!self.search_term.is_empty() && !self.match_string(string))
-                            || !(self.loglevels[*level as usize - 1])
-                        {
-                            return;
-                        }
-
-                        let string_format = format!("[{}]: {}", level, string);
-
-                        match level {
-                            log::Level::Warn => ui.colored_label(Color32::YELLOW, string_format),
-                            log::Level::Error => ui.colored_label(Color32::RED, string_format),
-                            _ => ui.label(string_format),
-                        };
-
-                        logs_displayed += 1;
-                    });
+            .show_viewport(ui, |ui, viewport| {
+                self.update_row_tops(ui);
+                ui.set_height(self.rows_height);
+
+                let first = self
+                    .row_tops
+                    .partition_point(|&top| top <= viewport.min.y)
+                    .saturating_sub(1);
+                let end = self.row_tops.partition_point(|&top| top < viewport.max.y);
+                let Some(&first_top) = self.row_tops.get(first) else {
+                    return;
+                };
+
+                let rect = egui::Rect::from_x_y_ranges(
+                    ui.max_rect().x_range(),
+                    ui.max_rect().top() + first_top..=ui.max_rect().bottom(),
+                );
+                ui.allocate_ui_at_rect(rect, |ui| {
+                    ui.skip_ahead_auto_ids(first);
+                    for &index in &self.displayed[first..end] {
+                        let galley = self.line_cache[index].galley(ui, self.wrap_width);
+                        ui.add(egui::Label::new(galley));
+                    }
                 });
             });
 
         ui.horizontal(|ui| {
-            ui.label(format!(
-                "Log size: {}",
-                try_get_log(|logs| logs.len()).unwrap_or_default()
-            ));
+            ui.label(format!("Log size: {}", log_count()));
             ui.label(format!("Displayed: {}", logs_displayed));
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                 if ui.button("Copy").clicked() {
@@ -214,19 +282,185 @@ impl LoggerUi {
         });
     }
 
-    fn match_string(&self, string: &str) -> bool {
+    /// Brings the line cache and the displayed entries up to date with `logs`,
+    /// only formatting and filtering entries that were added since the last frame.
+    fn update_displayed(&mut self, logs: &GlobalLog) {
+        let generation = LOG_GENERATION.load(Ordering::Relaxed);
+        if self.line_cache_generation != generation || self.line_cache.len() > logs.len() {
+            self.line_cache.clear();
+            self.line_cache_generation = generation;
+            self.filter_key = None;
+        }
+        let cached = self.line_cache.len();
+        self.line_cache
+            .extend(logs[cached..].iter().map(|(level, string)| CachedLine {
+                level: *level,
+                text: format!("[{}]: {}", level, string),
+                message_lower: None,
+                height: None,
+            }));
+
+        let filter_unchanged = matches!(&self.filter_key, Some(key)
+            if key.loglevels == self.loglevels
+                && key.search_term == self.search_term
+                && key.search_case_sensitive == self.search_case_sensitive
+                && key.search_use_regex == self.search_use_regex);
+        if !filter_unchanged {
+            self.filter_key = Some(FilterKey {
+                loglevels: self.loglevels,
+                search_term: self.search_term.clone(),
+                search_case_sensitive: self.search_case_sensitive,
+                search_use_regex: self.search_use_regex,
+            });
+            if !self.search_is_case_insensitive() {
+                self.line_cache
+                    .iter_mut()
+                    .for_each(|line| line.message_lower = None);
+            }
+            self.displayed.clear();
+            self.filtered_len = 0;
+            self.row_tops.clear();
+            self.rows_height = 0.0;
+        }
+
+        if self.filtered_len == logs.len() {
+            return;
+        }
+        let search_term_lower = self.search_term_lower();
+        if search_term_lower.is_some() {
+            for (line, (_, string)) in self.line_cache[self.filtered_len..]
+                .iter_mut()
+                .zip(&logs[self.filtered_len..])
+            {
+                line.message_lower
+                    .get_or_insert_with(|| string.to_lowercase());
+            }
+        }
+        for (index, (level, string)) in logs.iter().enumerate().skip(self.filtered_len) {
+            if self.passes_filter(
+                *level,
+                string,
+                self.line_cache[index].message_lower.as_deref(),
+                search_term_lower.as_deref(),
+            ) {
+                self.displayed.push(index);
+            }
+        }
+        self.filtered_len = logs.len();
+    }
+
+    /// Shifts the caches after the first `dropped_entries` log entries were removed,
+    /// so the remaining entries don't need to be filtered and measured again.
+    fn drop_front(&mut self, dropped_entries: usize) {
+        if dropped_entries == 0 {
+            return;
+        }
+        drop(
+            self.line_cache
+                .drain(..dropped_entries.min(self.line_cache.len())),
+        );
+        self.filtered_len = self.filtered_len.saturating_sub(dropped_entries);
+
+        let dropped_displayed = self
+            .displayed
+            .partition_point(|&index| index < dropped_entries);
+        drop(self.displayed.drain(..dropped_displayed));
+        self.displayed
+            .iter_mut()
+            .for_each(|index| *index -= dropped_entries);
+
+        if let Some(&dropped_height) = self.row_tops.get(dropped_displayed) {
+            drop(self.row_tops.drain(..dropped_displayed));
+            self.row_tops
+                .iter_mut()
+                .for_each(|top| *top -= dropped_height);
+            self.rows_height -= dropped_height;
+        } else {
+            self.row_tops.clear();
+            self.rows_height = 0.0;
+        }
+    }
+
+    /// Measures the displayed entries that weren't laid out yet, remeasuring all of them
+    /// when the available width changed.
+    fn update_row_tops(&mut self, ui: &egui::Ui) {
+        let wrap_width = ui.available_width();
+        if wrap_width != self.wrap_width {
+            self.wrap_width = wrap_width;
+            self.line_cache
+                .iter_mut()
+                .for_each(|line| line.height = None);
+            self.row_tops.clear();
+            self.rows_height = 0.0;
+        }
+
+        let spacing = ui.spacing().item_spacing.y;
+        for &index in &self.displayed[self.row_tops.len()..] {
+            let line = &mut self.line_cache[index];
+            let height = match line.height {
+                Some(height) => height,
+                None => {
+                    let height = line.galley(ui, wrap_width).size().y;
+                    line.height = Some(height);
+                    height
+                }
+            };
+            let top = if self.row_tops.is_empty() {
+                0.0
+            } else {
+                self.rows_height + spacing
+            };
+            self.row_tops.push(top);
+            self.rows_height = top + height;
+        }
+    }
+
+    /// Whether the search is a non-empty, case-insensitive, non-regex search.
+    fn search_is_case_insensitive(&self) -> bool {
+        !self.search_term.is_empty() && !self.search_case_sensitive && !self.search_use_regex
+    }
+
+    /// The lowercased search term, only computed for a non-empty, case-insensitive,
+    /// non-regex search, so it isn't reallocated for every line.
+    fn search_term_lower(&self) -> Option<String> {
+        self.search_is_case_insensitive()
+            .then(|| self.search_term.to_lowercase())
+    }
+
+    /// Whether an entry passes the level filter and the search, see [`Self::match_string()`].
+    fn passes_filter(
+        &self,
+        level: log::Level,
+        string: &str,
+        string_lower: Option<&str>,
+        search_term_lower: Option<&str>,
+    ) -> bool {
+        self.loglevels[level as usize - 1]
+            && (self.search_term.is_empty()
+                || self.match_string(string, string_lower, search_term_lower))
+    }
+
+    /// `string_lower` is the cached lowercased `string`, if any, and `search_term_lower`
+    /// the result of [`Self::search_term_lower()`].
+    fn match_string(
+        &self,
+        string: &str,
+        string_lower: Option<&str>,
+        search_term_lower: Option<&str>,
+    ) -> bool {
         if self.search_use_regex {
             if let Some(matcher) = &self.regex {
                 matcher.is_match(string)
             } else {
                 false
             }
-        } else if self.search_case_sensitive {
-            string.contains(&self.search_term)
+        } else if let Some(search_term_lower) = search_term_lower {
+            match string_lower {
+                Some(string_lower) => string_lower.contains(search_term_lower),
+                None => string.to_lowercase().contains(search_term_lower),
+            }
         } else {
-            string
-                .to_lowercase()
-                .contains(&self.search_term.to_lowercase())
+            string.contains(&self.search_term)
         }
     }
 }
@@ -240,3 +474,211 @@ pub fn logger_ui(ui: &mut egui::Ui) {
         ui.colored_label(Color32::RED, "Something went wrong loading the log");
     }
 }
+
+/// Returns a snapshot of the currently captured log lines.
+/// Returns an empty [`Vec`] if the log couldn't be accessed.
+pub fn logs() -> Vec<(log::Level, String)> {
+    try_get_log(|logs| logs.clone()).unwrap_or_default()
+}
+
+/// Returns the captured log lines and empties the log.
+/// Returns an empty [`Vec`] if the log couldn't be accessed.
+pub fn drain() -> Vec<(log::Level, String)> {
+    try_mut_log(|logs| {
+        invalidate_line_cache();
+        std::mem::take(logs)
+    })
+    .unwrap_or_default()
+}
+
+/// Returns the number of captured log lines.
+/// Returns `0` if the log couldn't be accessed.
+pub fn log_count() -> usize {
+    try_get_log(|logs| logs.len()).unwrap_or_default()
+}
+
+/// Removes all captured log lines.
+pub fn clear() {
+    try_mut_log(|logs| {
+        logs.clear();
+        invalidate_line_cache();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Log;
+
+    use super::*;
+
+    /// Serializes the tests that touch the global log.
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn lock_log() -> std::sync::MutexGuard<'static, ()> {
+        TEST_LOCK
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn log_line(level: log::Level, message: &str) {
+        EguiLogger.log(
+            &log::Record::builder()
+                .level(level)
+                .args(format_args!("{}", message))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn read_and_drain_logs() {
+        let _guard = lock_log();
+        clear();
+        log_line(log::Level::Info, "first");
+        log_line(log::Level::Error, "second");
+        assert_eq!(log_count(), 2);
+
+        let expected = vec![
+            (log::Level::Info, "first".to_owned()),
+            (log::Level::Error, "second".to_owned()),
+        ];
+        assert_eq!(logs(), expected);
+        assert_eq!(log_count(), 2);
+
+        assert_eq!(drain(), expected);
+        assert_eq!(log_count(), 0);
+        assert!(logs().is_empty());
+    }
+
+    fn entries(entries: &[(log::Level, &str)]) -> GlobalLog {
+        entries
+            .iter()
+            .map(|&(level, message)| (level, message.to_owned()))
+            .collect()
+    }
+
+    fn displayed_text(logger_ui: &LoggerUi) -> Vec<&str> {
+        logger_ui
+            .displayed
+            .iter()
+            .map(|&index| logger_ui.line_cache[index].text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn line_cache_follows_log() {
+        let _guard = lock_log();
+        let mut logger_ui = LoggerUi::default();
+
+        let mut logs = entries(&[(log::Level::Info, "a"), (log::Level::Debug, "b")]);
+        logger_ui.update_displayed(&logs);
+        assert_eq!(logger_ui.line_cache.len(), 2);
+        assert_eq!(displayed_text(&logger_ui), ["[INFO]: a"]);
+
+        // Appended lines are filtered on their own.
+        logs.push((log::Level::Error, "c".to_owned()));
+        logger_ui.update_displayed(&logs);
+        assert_eq!(logger_ui.line_cache.len(), 3);
+        assert_eq!(displayed_text(&logger_ui), ["[INFO]: a", "[ERROR]: c"]);
+
+        // Changing the levels or the search filters again.
+        logger_ui.loglevels[log::Level::Debug as usize - 1] = true;
+        logger_ui.update_displayed(&logs);
+        assert_eq!(
+            displayed_text(&logger_ui),
+            ["[INFO]: a", "[DEBUG]: b", "[ERROR]: c"]
+        );
+
+        logger_ui.search_term = "C".to_owned();
+        logger_ui.update_displayed(&logs);
+        assert_eq!(displayed_text(&logger_ui), ["[ERROR]: c"]);
+        assert_eq!(logger_ui.line_cache[2].message_lower.as_deref(), Some("c"));
+
+        logger_ui.search_term.clear();
+        logger_ui.update_displayed(&logs);
+        assert_eq!(logger_ui.displayed, [0, 1, 2]);
+        assert!(logger_ui
+            .line_cache
+            .iter()
+            .all(|line| line.message_lower.is_none()));
+
+        // Trimming old entries shifts the caches.
+        logger_ui.row_tops = vec![0.0, 13.0, 26.0];
+        logger_ui.rows_height = 36.0;
+        drop(logs.drain(..1));
+        logger_ui.drop_front(1);
+        assert_eq!(logger_ui.displayed, [0, 1]);
+        assert_eq!(logger_ui.row_tops, [0.0, 13.0]);
+        assert_eq!(logger_ui.rows_height, 23.0);
+        logs.push((log::Level::Warn, "d".to_owned()));
+        logger_ui.update_displayed(&logs);
+        assert_eq!(logger_ui.line_cache.len(), 3);
+        assert_eq!(
+            displayed_text(&logger_ui),
+            ["[DEBUG]: b", "[ERROR]: c", "[WARN]: d"]
+        );
+
+        // Sorting, clearing and draining rebuild the cache, even at the same length.
+        logs.sort();
+        invalidate_line_cache();
+        logger_ui.update_displayed(&logs);
+        assert_eq!(
+            displayed_text(&logger_ui),
+            ["[ERROR]: c", "[WARN]: d", "[DEBUG]: b"]
+        );
+
+        clear();
+        logs = entries(&[
+            (log::Level::Info, "e"),
+            (log::Level::Info, "f"),
+            (log::Level::Info, "g"),
+        ]);
+        logger_ui.update_displayed(&logs);
+        assert_eq!(
+            displayed_text(&logger_ui),
+            ["[INFO]: e", "[INFO]: f", "[INFO]: g"]
+        );
+
+        drop(drain());
+        logs = entries(&[
+            (log::Level::Warn, "h"),
+            (log::Level::Warn, "i"),
+            (log::Level::Warn, "j"),
+        ]);
+        logger_ui.update_displayed(&logs);
+        assert_eq!(logger_ui.line_cache.len(), 3);
+        assert_eq!(
+            displayed_text(&logger_ui),
+            ["[WARN]: h", "[WARN]: i", "[WARN]: j"]
+        );
+    }
+
+    #[test]
+    fn search_matching() {
+        let mut logger_ui = LoggerUi::default();
+
+        // An empty search lets everything at an enabled level through.
+        assert_eq!(logger_ui.search_term_lower(), None);
+        for level in LEVELS {
+            assert_eq!(
+                logger_ui.passes_filter(level, "Request 42", None, None),
+                logger_ui.loglevels[level as usize - 1]
+            );
+        }
+
+        logger_ui.search_term = "REQUEST".to_owned();
+        let search_term_lower = logger_ui.search_term_lower();
+        assert_eq!(search_term_lower.as_deref(), Some("request"));
+        assert!(logger_ui.match_string("Request 42", None, search_term_lower.as_deref()));
+        assert!(logger_ui.match_string(
+            "Request 42",
+            Some("request 42"),
+            search_term_lower.as_deref()
+        ));
+
+        logger_ui.search_case_sensitive = true;
+        let search_term_lower = logger_ui.search_term_lower();
+        assert_eq!(search_term_lower, None);
+        assert!(!logger_ui.match_string("Request 42", None, None));
+        assert!(logger_ui.match_string("REQUEST 42", None, None));
+    }
+}